@@ -0,0 +1,128 @@
+// Username/password verification and short-lived session tokens.
+//
+// Passwords are stored as Argon2id PHC strings -- each one already
+// carries its own random salt, so there's no separate salt table to
+// keep in sync with the user registry.
+
+use argon2::password_hash::rand_core::RngCore;
+use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A fixed Argon2id PHC string with no corresponding real account,
+/// hashed against when `verify` is called for an unknown username --
+/// see `verify` for why.
+fn dummy_hash() -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(b"not-a-real-password", &salt)
+        .expect("hashing the fixed dummy password should never fail")
+        .to_string()
+}
+
+/// Username -> Argon2id password hash, loaded once at startup.
+pub struct AuthRegistry {
+    users: HashMap<String, String>,
+    dummy_hash: String,
+}
+
+impl AuthRegistry {
+    pub fn new(users: HashMap<String, String>) -> Self {
+        Self {
+            users,
+            dummy_hash: dummy_hash(),
+        }
+    }
+
+    /// A single built-in demo account (`admin`/`admin`) so the server
+    /// is usable out of the box. A real deployment should load its
+    /// `users` registry from a file or secret store instead.
+    pub fn with_demo_user() -> Self {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(b"admin", &salt)
+            .expect("hashing the built-in demo password should never fail")
+            .to_string();
+        let mut users = HashMap::new();
+        users.insert("admin".to_string(), hash);
+        Self::new(users)
+    }
+
+    /// Verify `password` against the stored Argon2id hash for
+    /// `username`. An unknown username still pays the full Argon2id
+    /// cost against a fixed dummy hash rather than returning
+    /// immediately, so callers can't distinguish "no such user" from
+    /// "wrong password" by timing the response.
+    pub fn verify(&self, username: &str, password: &str) -> bool {
+        let known_user = self.users.contains_key(username);
+        let stored_hash = self.users.get(username).unwrap_or(&self.dummy_hash);
+        let Ok(parsed_hash) = PasswordHash::new(stored_hash) else {
+            return false;
+        };
+        let password_matches = Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok();
+        known_user && password_matches
+    }
+}
+
+pub type SessionToken = String;
+
+/// Issues and validates short-lived tokens that let a client resume
+/// an authenticated session (after a reconnect, say) without sending
+/// its password again.
+pub struct TokenStore {
+    tokens: Mutex<HashMap<SessionToken, (String, Instant)>>,
+    ttl: Duration,
+}
+
+impl TokenStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            tokens: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Issue a fresh token for `username`, valid until `ttl` elapses.
+    /// Drawn from `OsRng` rather than a sequential id or the clock, so
+    /// a token can't be narrowed down by an attacker who can observe
+    /// (or roughly bound) when a connection authenticated.
+    pub fn issue(&self, username: &str) -> SessionToken {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(token.clone(), (username.to_string(), Instant::now()));
+        token
+    }
+
+    /// Resume a session from `token`, returning the username it was
+    /// issued to if the token is known and not yet expired.
+    pub fn resume(&self, token: &str) -> Option<String> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let (username, issued_at) = tokens.get(token)?;
+        if issued_at.elapsed() > self.ttl {
+            tokens.remove(token);
+            return None;
+        }
+        Some(username.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_rejects_unknown_user_and_wrong_password() {
+        let registry = AuthRegistry::with_demo_user();
+        assert!(registry.verify("admin", "admin"));
+        assert!(!registry.verify("admin", "wrong"));
+        assert!(!registry.verify("nobody", "admin"));
+    }
+}
@@ -0,0 +1,217 @@
+// Syndicate-style dataspace: a shared, ref-counted multiset of
+// asserted structured values with pattern-based observation.
+//
+// Clients `assert`/`retract` `Value`s and `observe` a pattern; `observe`
+// immediately replays every currently-matching assertion and thereafter
+// the dataspace pushes an event to the observer whenever a newly
+// asserted value matches, or a matching value is fully retracted.
+
+use crate::value::Value;
+
+/// Identifies the connection on whose behalf an assertion or
+/// observation was made, so per-connection bookkeeping (disconnect
+/// cleanup) can find everything that connection is responsible for.
+pub type ConnectionId = u64;
+
+/// A single asserted fact plus the connections that currently hold an
+/// assertion on it. The same value asserted twice by the same (or
+/// different) connections must be retracted that many times before it
+/// disappears.
+struct Fact {
+    value: Value,
+    asserted_by: Vec<ConnectionId>,
+}
+
+struct Observer {
+    conn: ConnectionId,
+    pattern: Value,
+    sink: Box<dyn Fn(Value) + Send>,
+}
+
+#[derive(Default)]
+pub struct Dataspace {
+    facts: Vec<Fact>,
+    observers: Vec<Observer>,
+}
+
+impl Dataspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an assertion of `value` by `conn`. Notifies any observer
+    /// whose pattern matches, but only on the transition from
+    /// unasserted to asserted -- a duplicate assertion just bumps the
+    /// fact's reference count.
+    pub fn assert(&mut self, conn: ConnectionId, value: Value) {
+        if let Some(fact) = self.facts.iter_mut().find(|f| f.value == value) {
+            fact.asserted_by.push(conn);
+            return;
+        }
+        self.facts.push(Fact {
+            value: value.clone(),
+            asserted_by: vec![conn],
+        });
+        self.notify_matching("asserted", &value);
+    }
+
+    /// Remove one of `conn`'s assertions of `value`. The fact only
+    /// disappears (and observers only get a `retracted` event) once
+    /// every asserting connection has retracted it.
+    pub fn retract(&mut self, conn: ConnectionId, value: Value) {
+        let Some(pos) = self.facts.iter().position(|f| f.value == value) else {
+            return;
+        };
+        if let Some(idx) = self.facts[pos].asserted_by.iter().position(|&c| c == conn) {
+            self.facts[pos].asserted_by.remove(idx);
+        }
+        if self.facts[pos].asserted_by.is_empty() {
+            let removed = self.facts.remove(pos);
+            self.notify_matching("retracted", &removed.value);
+        }
+    }
+
+    /// Register `sink` as an observer of `pattern` on behalf of `conn`,
+    /// returning every currently-asserted value that already matches
+    /// so the caller can replay them before live events start arriving.
+    pub fn observe(
+        &mut self,
+        conn: ConnectionId,
+        pattern: Value,
+        sink: Box<dyn Fn(Value) + Send>,
+    ) -> Vec<Value> {
+        let replay: Vec<Value> = self
+            .facts
+            .iter()
+            .filter(|f| matches(&pattern, &f.value))
+            .map(|f| f.value.clone())
+            .collect();
+        self.observers.push(Observer { conn, pattern, sink });
+        replay
+    }
+
+    /// Retract everything `conn` asserted and drop its observers. Must
+    /// be called when a connection disconnects, or its facts and taps
+    /// would outlive the socket that created them.
+    pub fn disconnect(&mut self, conn: ConnectionId) {
+        self.observers.retain(|o| o.conn != conn);
+
+        let mut to_retract = Vec::new();
+        for fact in &self.facts {
+            let occurrences = fact.asserted_by.iter().filter(|&&c| c == conn).count();
+            to_retract.extend(std::iter::repeat_n(fact.value.clone(), occurrences));
+        }
+        for value in to_retract {
+            self.retract(conn, value);
+        }
+    }
+
+    fn notify_matching(&self, kind: &str, value: &Value) {
+        for observer in &self.observers {
+            if matches(&observer.pattern, value) {
+                (observer.sink)(event(kind, value));
+            }
+        }
+    }
+}
+
+fn event(kind: &str, value: &Value) -> Value {
+    Value::Record {
+        label: Box::new(Value::Symbol(kind.to_string())),
+        fields: vec![value.clone()],
+    }
+}
+
+/// Does `candidate` match `pattern`? The reserved symbol `_` matches
+/// any sub-value; everything else must match exactly by deep equality,
+/// recursing structurally into sequences and records.
+fn matches(pattern: &Value, candidate: &Value) -> bool {
+    if matches!(pattern, Value::Symbol(s) if s == "_") {
+        return true;
+    }
+    match (pattern, candidate) {
+        (Value::Sequence(ps), Value::Sequence(cs)) if ps.len() == cs.len() => {
+            ps.iter().zip(cs.iter()).all(|(p, c)| matches(p, c))
+        }
+        (
+            Value::Record { label: pl, fields: pf },
+            Value::Record { label: cl, fields: cf },
+        ) if pf.len() == cf.len() => matches(pl, cl) && pf.iter().zip(cf.iter()).all(|(p, c)| matches(p, c)),
+        _ => pattern == candidate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn sym(s: &str) -> Value {
+        Value::Symbol(s.to_string())
+    }
+
+    type Received = Arc<Mutex<Vec<Value>>>;
+
+    fn sink() -> (Box<dyn Fn(Value) + Send>, Received) {
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let for_sink = Arc::clone(&received);
+        let sink: Box<dyn Fn(Value) + Send> = Box::new(move |v| for_sink.lock().unwrap().push(v));
+        (sink, received)
+    }
+
+    #[test]
+    fn observe_replays_existing_facts_and_notifies_new_ones() {
+        let mut ds = Dataspace::new();
+        ds.assert(1, sym("a"));
+
+        let (sink, received) = sink();
+        let replay = ds.observe(2, sym("_"), sink);
+        assert_eq!(replay, vec![sym("a")]);
+
+        ds.assert(1, sym("b"));
+        assert_eq!(received.lock().unwrap().as_slice(), &[event("asserted", &sym("b"))]);
+    }
+
+    #[test]
+    fn refcounted_assert_requires_matching_retracts() {
+        let mut ds = Dataspace::new();
+        ds.assert(1, sym("a"));
+        ds.assert(2, sym("a")); // second assertion of the same fact by a different connection
+
+        let (sink, received) = sink();
+        ds.observe(3, sym("_"), sink);
+
+        ds.retract(1, sym("a"));
+        assert!(received.lock().unwrap().is_empty(), "fact is still held by connection 2");
+
+        ds.retract(2, sym("a"));
+        assert_eq!(received.lock().unwrap().as_slice(), &[event("retracted", &sym("a"))]);
+    }
+
+    #[test]
+    fn disconnect_retracts_every_occurrence_asserted_by_that_connection() {
+        let mut ds = Dataspace::new();
+        ds.assert(1, sym("a"));
+        ds.assert(1, sym("a")); // asserted twice by the same connection
+
+        let (sink, received) = sink();
+        ds.observe(2, sym("_"), sink);
+
+        ds.disconnect(1);
+        assert_eq!(received.lock().unwrap().as_slice(), &[event("retracted", &sym("a"))]);
+    }
+
+    #[test]
+    fn disconnect_drops_its_own_observers() {
+        let mut ds = Dataspace::new();
+        let (sink, received) = sink();
+        ds.observe(1, sym("_"), sink);
+
+        ds.disconnect(1);
+
+        // connection 1's observer was dropped by its own disconnect, so
+        // subsequent activity must not reach it.
+        ds.assert(2, sym("b"));
+        assert!(received.lock().unwrap().is_empty());
+    }
+}
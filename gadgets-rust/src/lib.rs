@@ -3,8 +3,8 @@
 
 use std::any::Any;
 use std::collections::HashMap;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// Core effect types that gadgets can emit
 #[derive(Debug)]
@@ -52,48 +52,68 @@ pub trait SemanticExtension {
     }
 }
 
+/// Registry of a `TappingExtension`'s live taps, keyed by the id
+/// `TapHandle` uses to remove its own entry on drop.
+type TapRegistry = Arc<Mutex<HashMap<u64, Box<dyn FnMut(Effect) + Send>>>>;
+
 /// Tapping extension - allows multiple observers
+///
+/// Gadgets are shared across connection-handling threads (see
+/// `GadgetHandler: Send` in `server.rs`), so the tap registry has to be
+/// thread-safe rather than the `Rc<RefCell<_>>` a single-threaded
+/// design would use.
 pub struct TappingExtension {
-    taps: Rc<RefCell<Vec<Box<dyn FnMut(Effect)>>>>,
+    taps: TapRegistry,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Default for TappingExtension {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TappingExtension {
     pub fn new() -> Self {
         Self {
-            taps: Rc::new(RefCell::new(Vec::new())),
+            taps: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
         }
     }
 
+    /// Register `f` to be called with every `Effect` this extension's
+    /// owner emits. `f` is called until the returned `TapHandle` is
+    /// dropped, at which point it's removed from the registry -- the
+    /// id makes that removal precise even if other taps were added or
+    /// removed in the meantime.
     pub fn tap<F>(&self, f: F) -> TapHandle
     where
-        F: FnMut(Effect) + 'static,
+        F: FnMut(Effect) + Send + 'static,
     {
-        let mut taps = self.taps.borrow_mut();
-        let id = taps.len();
-        taps.push(Box::new(f));
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.taps.lock().unwrap().insert(id, Box::new(f));
         TapHandle {
             id,
-            taps: Rc::clone(&self.taps),
+            taps: Arc::clone(&self.taps),
         }
     }
 }
 
 pub struct TapHandle {
-    id: usize,
-    taps: Rc<RefCell<Vec<Box<dyn FnMut(Effect)>>>>,
+    id: u64,
+    taps: TapRegistry,
 }
 
 impl Drop for TapHandle {
     fn drop(&mut self) {
-        // In production, would track and remove specific tap
-        // For simplicity, we're not implementing removal
+        self.taps.lock().unwrap().remove(&self.id);
     }
 }
 
 impl SemanticExtension for TappingExtension {
     fn wrap_emit(&self, effect: Effect) -> Effect {
-        let mut taps = self.taps.borrow_mut();
-        for tap in taps.iter_mut() {
+        let mut taps = self.taps.lock().unwrap();
+        for tap in taps.values_mut() {
             tap(effect.clone());
         }
         effect
@@ -101,6 +121,7 @@ impl SemanticExtension for TappingExtension {
 }
 
 /// A basic gadget implementation with consider/act pattern
+#[allow(clippy::type_complexity)]
 pub struct BasicGadget<S, I> {
     state: S,
     consider: Box<dyn Fn(&S, &I) -> ConsiderResult>,
@@ -109,6 +130,7 @@ pub struct BasicGadget<S, I> {
 }
 
 impl<S: Clone, I> BasicGadget<S, I> {
+    #[allow(clippy::type_complexity)]
     pub fn new(
         initial: S,
         consider: Box<dyn Fn(&S, &I) -> ConsiderResult>,
@@ -158,22 +180,32 @@ impl<S: Clone, I> Gadget for BasicGadget<S, I> {
         for ext in &self.extensions {
             final_effect = ext.wrap_emit(final_effect);
         }
-        // In real implementation, would send to external system
-        println!("Emitted: {:?}", final_effect);
+        let _ = final_effect;
     }
 }
 
 /// Example: MaxCell gadget
 pub struct MaxCell {
     value: i32,
+    taps: TappingExtension,
 }
 
 impl MaxCell {
     pub fn new(initial: i32) -> Self {
         Self {
             value: initial,
+            taps: TappingExtension::new(),
         }
     }
+
+    /// Register `f` to be called with every `Effect` this gadget
+    /// emits, until the returned handle is dropped.
+    pub fn tap<F>(&self, f: F) -> TapHandle
+    where
+        F: FnMut(Effect) + Send + 'static,
+    {
+        self.taps.tap(f)
+    }
 }
 
 impl Gadget for MaxCell {
@@ -198,18 +230,37 @@ impl Gadget for MaxCell {
     }
 
     fn emit(&mut self, effect: Effect) {
-        println!("MaxCell emitted: {:?}", effect);
+        self.taps.wrap_emit(effect);
     }
 }
 
 /// Example: Counter gadget
 pub struct Counter {
     count: i32,
+    taps: TappingExtension,
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Counter {
     pub fn new() -> Self {
-        Self { count: 0 }
+        Self {
+            count: 0,
+            taps: TappingExtension::new(),
+        }
+    }
+
+    /// Register `f` to be called with every `Effect` this gadget
+    /// emits, until the returned handle is dropped.
+    pub fn tap<F>(&self, f: F) -> TapHandle
+    where
+        F: FnMut(Effect) + Send + 'static,
+    {
+        self.taps.tap(f)
     }
 }
 
@@ -246,6 +297,26 @@ impl Gadget for Counter {
     }
 
     fn emit(&mut self, effect: Effect) {
-        println!("Counter emitted: {:?}", effect);
+        self.taps.wrap_emit(effect);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropping_tap_handle_stops_further_effects() {
+        let taps = TappingExtension::new();
+        let received = Arc::new(Mutex::new(0u32));
+        let for_tap = Arc::clone(&received);
+        let handle = taps.tap(move |_effect| *for_tap.lock().unwrap() += 1);
+
+        taps.wrap_emit(Effect::Noop);
+        assert_eq!(*received.lock().unwrap(), 1);
+
+        drop(handle);
+        taps.wrap_emit(Effect::Noop);
+        assert_eq!(*received.lock().unwrap(), 1, "a dropped tap must not keep receiving effects");
     }
 }
\ No newline at end of file
@@ -1,5 +1,8 @@
+mod auth;
+mod dataspace;
 mod lib;
 mod server;
+mod value;
 
 use server::GadgetServer;
 
@@ -7,9 +10,16 @@ fn main() {
     println!("Starting Rust Gadget Server...");
 
     let server = GadgetServer::new();
+    let addr = "127.0.0.1:9999";
 
-    // Start server on localhost:9999
-    if let Err(e) = server.start("127.0.0.1:9999") {
+    // GADGET_SERVER_MODE=polled selects the single-threaded poll(2)
+    // event loop (see `GadgetServer::start_polled`) instead of the
+    // default thread-per-connection model.
+    let result = match std::env::var("GADGET_SERVER_MODE").as_deref() {
+        Ok("polled") => server.start_polled(addr),
+        _ => server.start(addr),
+    };
+    if let Err(e) = result {
         eprintln!("Server error: {}", e);
     }
 }
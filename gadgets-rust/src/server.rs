@@ -1,57 +1,297 @@
 // TCP server for gadget communication
-// Simple line-based protocol: GADGET_NAME COMMAND DATA
+//
+// Two wire framings are supported and auto-detected per command:
+//   - line protocol: "GADGET_NAME COMMAND [DATA]\n", DATA read as text
+//   - binary protocol: a `Value::Sequence([Symbol(gadget), Symbol(action), data?])`
+//     written with the canonical binary encoding from the `value` module
+// A leading tag byte (>= 0x80, see `Value::looks_like_binary_tag`) selects
+// binary mode; anything else falls back to the line protocol.
 
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
-use crate::lib::{Counter, Effect, Gadget, MaxCell};
+use crate::auth::{AuthRegistry, TokenStore};
+use crate::dataspace::{ConnectionId, Dataspace};
+use crate::lib::{Counter, Effect, Gadget, MaxCell, TapHandle};
+use crate::value::Value;
+
+/// How long an `AUTH`-issued session token stays valid for `RESUME`.
+const TOKEN_TTL: Duration = Duration::from_secs(300);
+
+/// Actions gated behind an authenticated connection: both the ones
+/// that mutate gadget state (`receive`/`create`/`assert`/`retract`)
+/// and the ones that expose it (`observe`'s replay-and-live-feed,
+/// `subscribe`'s live effect feed, and `unsubscribe` for symmetry with
+/// it) -- an unauthenticated connection getting a live read of another
+/// connection's dataspace facts or gadget effects is as much a hole as
+/// being able to mutate them. `current`/`list` stay read-only,
+/// one-shot, and open to unauthenticated connections.
+const AUTH_REQUIRED_ACTIONS: &[&str] =
+    &["receive", "create", "assert", "retract", "observe", "subscribe", "unsubscribe"];
+
+fn requires_auth(action: &str) -> bool {
+    AUTH_REQUIRED_ACTIONS.contains(&action)
+}
+
+/// How an asynchronously-pushed event (dataspace `observe`
+/// notifications and `subscribe` tap effects) reaches the connection
+/// that registered for it.
+type OutboundSender = Sender<Value>;
+
+/// Protocol version this build of the server speaks. Bumped whenever
+/// the command grammar changes in a way old clients couldn't parse.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Gadget types and actions a client can discover via the `HELLO`
+/// handshake's capability list.
+const KNOWN_GADGET_TYPES: &str = "counter,maxcell,dataspace";
+const KNOWN_ACTIONS: &str = "receive,current,create,list,assert,retract,observe,subscribe,unsubscribe";
+
+/// Response framing a connection has negotiated: `process_command`
+/// answers `Line` sessions with human-readable text and `Json`
+/// sessions with structured JSON objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseFormat {
+    Line,
+    Json,
+}
+
+/// Per-connection state: established by the `HELLO` handshake, then
+/// updated in place once the connection authenticates via `AUTH`/`RESUME`.
+struct Session {
+    version: u32,
+    format: ResponseFormat,
+    authenticated: bool,
+    username: Option<String>,
+}
+
+/// Parse the mandatory first line of a connection: `HELLO <version>
+/// [format=line|json]`. Returns `None` if the client sent anything
+/// else, which the caller treats as a protocol violation.
+fn parse_hello(line: &str) -> Option<Session> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.first() != Some(&"HELLO") {
+        return None;
+    }
+    let client_version: u32 = parts.get(1)?.parse().ok()?;
+
+    let mut format = ResponseFormat::Line;
+    for option in &parts[2..] {
+        if let Some(value) = option.strip_prefix("format=") {
+            format = match value {
+                "json" => ResponseFormat::Json,
+                _ => ResponseFormat::Line,
+            };
+        }
+    }
+
+    Some(Session {
+        version: client_version.min(PROTOCOL_VERSION),
+        format,
+        authenticated: false,
+        username: None,
+    })
+}
+
+/// Build the server's handshake reply: its own supported version, the
+/// version both sides agreed to use, and what it can do.
+fn hello_reply(session: &Session) -> String {
+    format!(
+        "HELLO {} {} capabilities=gadgets:{};actions:{}",
+        PROTOCOL_VERSION, session.version, KNOWN_GADGET_TYPES, KNOWN_ACTIONS,
+    )
+}
 
 pub struct GadgetServer {
     gadgets: Arc<Mutex<HashMap<String, Box<dyn GadgetHandler>>>>,
+    next_connection_id: AtomicU64,
+    auth: Arc<AuthRegistry>,
+    tokens: Arc<TokenStore>,
 }
 
 // Trait for handling gadgets over the network
 trait GadgetHandler: Send {
-    fn receive(&mut self, data: &str) -> String;
-    fn current(&self) -> String;
+    fn receive(&mut self, data: Value) -> Value;
+    fn current(&self) -> Value;
+
+    /// The following default to "unsupported" since only the
+    /// `Dataspace` gadget currently implements them.
+    fn assert(&mut self, _conn: ConnectionId, _data: Value) -> Value {
+        error_record("unsupported_action", "this gadget does not support assert")
+    }
+    fn retract(&mut self, _conn: ConnectionId, _data: Value) -> Value {
+        error_record("unsupported_action", "this gadget does not support retract")
+    }
+    fn observe(&mut self, _conn: ConnectionId, _pattern: Value, _sink: OutboundSender) -> Value {
+        error_record("unsupported_action", "this gadget does not support observe")
+    }
+
+    /// Start pushing every `Effect` this gadget emits to `sink` as a
+    /// live `EVENT`, until `unsubscribe` is called for the same
+    /// connection (or it disconnects). Unsupported by default; only
+    /// gadgets that back onto a `TappingExtension` override it.
+    fn subscribe(&mut self, _conn: ConnectionId, _sink: OutboundSender) -> Value {
+        error_record("unsupported_action", "this gadget does not support subscribe")
+    }
+
+    /// Stop pushing events to `conn` that a prior `subscribe` started.
+    /// A no-op (not an error) if `conn` had no active subscription.
+    fn unsubscribe(&mut self, _conn: ConnectionId) -> Value {
+        Value::Symbol("ok".to_string())
+    }
+
+    /// Called for every registered gadget when a connection goes away,
+    /// so gadgets holding per-connection state (assertions, observers)
+    /// can clean it up. No-op by default.
+    fn disconnect(&mut self, _conn: ConnectionId) {}
 }
 
 // Wrapper for Counter
 struct CounterHandler {
     counter: Counter,
+    subs: HashMap<ConnectionId, TapHandle>,
 }
 
 impl GadgetHandler for CounterHandler {
-    fn receive(&mut self, data: &str) -> String {
-        self.counter.receive(data.to_string());
-        format!("{}", self.counter.current())
+    fn receive(&mut self, data: Value) -> Value {
+        let command = match &data {
+            Value::Symbol(s) => s.clone(),
+            Value::String(s) => s.clone(),
+            other => return error_record("invalid_command", &format!("expected a symbol, got {}", other)),
+        };
+        let before = self.counter.current();
+        self.counter.receive(command);
+        let after = self.counter.current();
+        let effect = if after != before {
+            Effect::Changed(after.to_string())
+        } else {
+            Effect::Noop
+        };
+        Value::from_effect(&effect)
     }
 
-    fn current(&self) -> String {
-        format!("{}", self.counter.current())
+    fn current(&self) -> Value {
+        Value::SignedInteger(self.counter.current() as i64)
+    }
+
+    fn subscribe(&mut self, conn: ConnectionId, sink: OutboundSender) -> Value {
+        let handle = self.counter.tap(move |effect| {
+            let _ = sink.send(Value::from_effect(&effect));
+        });
+        self.subs.insert(conn, handle);
+        Value::Symbol("ok".to_string())
+    }
+
+    fn unsubscribe(&mut self, conn: ConnectionId) -> Value {
+        self.subs.remove(&conn);
+        Value::Symbol("ok".to_string())
+    }
+
+    fn disconnect(&mut self, conn: ConnectionId) {
+        self.subs.remove(&conn);
     }
 }
 
 // Wrapper for MaxCell
 struct MaxCellHandler {
     maxcell: MaxCell,
+    subs: HashMap<ConnectionId, TapHandle>,
 }
 
 impl GadgetHandler for MaxCellHandler {
-    fn receive(&mut self, data: &str) -> String {
-        if let Ok(value) = data.parse::<i32>() {
-            self.maxcell.receive(value);
-            format!("{}", self.maxcell.current())
-        } else {
-            format!("ERROR: Invalid integer")
+    fn receive(&mut self, data: Value) -> Value {
+        let parsed = match &data {
+            Value::SignedInteger(n) => Some(*n as i32),
+            Value::String(s) => s.parse::<i32>().ok(),
+            _ => None,
+        };
+        match parsed {
+            Some(value) => {
+                let before = self.maxcell.current();
+                self.maxcell.receive(value);
+                let after = self.maxcell.current();
+                let effect = if after != before {
+                    Effect::Changed(after.to_string())
+                } else {
+                    Effect::Noop
+                };
+                Value::from_effect(&effect)
+            }
+            None => error_record("invalid_integer", "expected a signed integer"),
         }
     }
 
-    fn current(&self) -> String {
-        format!("{}", self.maxcell.current())
+    fn current(&self) -> Value {
+        Value::SignedInteger(self.maxcell.current() as i64)
+    }
+
+    fn subscribe(&mut self, conn: ConnectionId, sink: OutboundSender) -> Value {
+        let handle = self.maxcell.tap(move |effect| {
+            let _ = sink.send(Value::from_effect(&effect));
+        });
+        self.subs.insert(conn, handle);
+        Value::Symbol("ok".to_string())
+    }
+
+    fn unsubscribe(&mut self, conn: ConnectionId) -> Value {
+        self.subs.remove(&conn);
+        Value::Symbol("ok".to_string())
+    }
+
+    fn disconnect(&mut self, conn: ConnectionId) {
+        self.subs.remove(&conn);
+    }
+}
+
+// Wrapper for Dataspace
+struct DataspaceHandler {
+    dataspace: Dataspace,
+}
+
+impl GadgetHandler for DataspaceHandler {
+    fn receive(&mut self, _data: Value) -> Value {
+        error_record("unsupported_action", "use assert/retract/observe on a dataspace")
+    }
+
+    fn current(&self) -> Value {
+        error_record("unsupported_action", "use observe to read a dataspace")
+    }
+
+    fn assert(&mut self, conn: ConnectionId, data: Value) -> Value {
+        self.dataspace.assert(conn, data);
+        Value::Symbol("ok".to_string())
+    }
+
+    fn retract(&mut self, conn: ConnectionId, data: Value) -> Value {
+        self.dataspace.retract(conn, data);
+        Value::Symbol("ok".to_string())
+    }
+
+    fn observe(&mut self, conn: ConnectionId, pattern: Value, sink: OutboundSender) -> Value {
+        let replay = self
+            .dataspace
+            .observe(conn, pattern, Box::new(move |event| {
+                let _ = sink.send(event);
+            }));
+        Value::Sequence(replay)
+    }
+
+    fn disconnect(&mut self, conn: ConnectionId) {
+        self.dataspace.disconnect(conn);
+    }
+}
+
+fn error_record(code: &str, detail: &str) -> Value {
+    Value::Record {
+        label: Box::new(Value::Symbol("error".to_string())),
+        fields: vec![Value::Symbol(code.to_string()), Value::String(detail.to_string())],
     }
 }
 
@@ -64,6 +304,7 @@ impl GadgetServer {
             "counter".to_string(),
             Box::new(CounterHandler {
                 counter: Counter::new(),
+                subs: HashMap::new(),
             }),
         );
 
@@ -71,11 +312,22 @@ impl GadgetServer {
             "maxcell".to_string(),
             Box::new(MaxCellHandler {
                 maxcell: MaxCell::new(0),
+                subs: HashMap::new(),
+            }),
+        );
+
+        gadgets.insert(
+            "dataspace".to_string(),
+            Box::new(DataspaceHandler {
+                dataspace: Dataspace::new(),
             }),
         );
 
         Self {
             gadgets: Arc::new(Mutex::new(gadgets)),
+            next_connection_id: AtomicU64::new(1),
+            auth: Arc::new(AuthRegistry::with_demo_user()),
+            tokens: Arc::new(TokenStore::new(TOKEN_TTL)),
         }
     }
 
@@ -87,8 +339,11 @@ impl GadgetServer {
             match stream {
                 Ok(stream) => {
                     let gadgets = Arc::clone(&self.gadgets);
+                    let auth = Arc::clone(&self.auth);
+                    let tokens = Arc::clone(&self.tokens);
+                    let conn_id = self.next_connection_id.fetch_add(1, Ordering::SeqCst);
                     thread::spawn(move || {
-                        handle_client(stream, gadgets);
+                        handle_client(stream, gadgets, conn_id, auth, tokens);
                     });
                 }
                 Err(e) => {
@@ -99,93 +354,790 @@ impl GadgetServer {
 
         Ok(())
     }
+
+    /// Alternative to `start`: one thread, all connections, driven by
+    /// readiness notifications from `poll(2)` instead of a thread per
+    /// connection. Only the line protocol is handled here -- the
+    /// handshake is line-based anyway, and non-blocking binary framing
+    /// would need the same incremental decoder `Value::read_from`
+    /// already has, just rewired around partial reads.
+    pub fn start_polled(&self, addr: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        println!("Gadget server (polled) listening on {}", addr);
+
+        let mut connections: Vec<PolledConnection> = Vec::new();
+
+        loop {
+            let mut pollfds = Vec::with_capacity(connections.len() + 1);
+            pollfds.push(libc::pollfd {
+                fd: listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            });
+            for conn in &connections {
+                let mut events = libc::POLLIN;
+                if conn.write_pos < conn.write_buf.len() {
+                    events |= libc::POLLOUT;
+                }
+                pollfds.push(libc::pollfd {
+                    fd: conn.stream.as_raw_fd(),
+                    events,
+                    revents: 0,
+                });
+            }
+
+            // A finite timeout, rather than blocking forever, so a
+            // gadget pushing an async event (dataspace `observe`) to
+            // an otherwise-idle connection still gets written out
+            // promptly instead of waiting for that socket to also
+            // become readable.
+            let ready = unsafe {
+                libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, 100)
+            };
+            if ready < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+
+            if pollfds[0].revents & libc::POLLIN != 0 {
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Err(e) = stream.set_nonblocking(true) {
+                                eprintln!("Error configuring accepted socket: {}", e);
+                                continue;
+                            }
+                            let conn_id = self.next_connection_id.fetch_add(1, Ordering::SeqCst);
+                            let (outbound_tx, outbound_rx) = mpsc::channel();
+                            connections.push(PolledConnection {
+                                stream,
+                                conn_id,
+                                session: None,
+                                read_buf: Vec::new(),
+                                write_buf: Vec::new(),
+                                write_pos: 0,
+                                outbound_tx,
+                                outbound_rx,
+                                closed: false,
+                            });
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                        Err(e) => {
+                            eprintln!("Error accepting connection: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            for (i, pfd) in pollfds.iter().enumerate().skip(1) {
+                let conn = &mut connections[i - 1];
+
+                if pfd.revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+                    conn.closed = true;
+                    continue;
+                }
+                if pfd.revents & libc::POLLIN != 0 {
+                    poll_read(conn, &self.gadgets, &self.auth, &self.tokens);
+                }
+                while let Ok(event) = conn.outbound_rx.try_recv() {
+                    conn.write_buf.extend_from_slice(format!("EVENT {}\n", event).as_bytes());
+                }
+                if !conn.closed && conn.write_pos < conn.write_buf.len() {
+                    poll_write(conn);
+                }
+            }
+
+            let gadgets = &self.gadgets;
+            let mut gadgets_lock = gadgets.lock().unwrap();
+            connections.retain(|conn| {
+                if conn.closed {
+                    for gadget in gadgets_lock.values_mut() {
+                        gadget.disconnect(conn.conn_id);
+                    }
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+    }
+}
+
+/// Per-connection state for `start_polled`: its own read/write
+/// buffers (since reads/writes are non-blocking and may be partial),
+/// and the channel async gadget events arrive on, mirroring the
+/// thread-per-connection outbound channel in `handle_client`.
+struct PolledConnection {
+    stream: TcpStream,
+    conn_id: ConnectionId,
+    session: Option<Session>,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    write_pos: usize,
+    outbound_tx: OutboundSender,
+    outbound_rx: mpsc::Receiver<Value>,
+    closed: bool,
+}
+
+/// Pull every complete `\n`-terminated line out of `buf`, leaving any
+/// trailing partial line (a command split across reads) in place for
+/// the next read to complete.
+fn drain_complete_lines(buf: &mut Vec<u8>) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+        let line_bytes: Vec<u8> = buf.drain(..=pos).collect();
+        if let Ok(line) = String::from_utf8(line_bytes) {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+fn poll_read(
+    conn: &mut PolledConnection,
+    gadgets: &Arc<Mutex<HashMap<String, Box<dyn GadgetHandler>>>>,
+    auth: &Arc<AuthRegistry>,
+    tokens: &Arc<TokenStore>,
+) {
+    let mut buf = [0u8; 4096];
+    loop {
+        match conn.stream.read(&mut buf) {
+            Ok(0) => {
+                conn.closed = true;
+                break;
+            }
+            Ok(n) => conn.read_buf.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(e) => {
+                eprintln!("Error reading from polled client: {}", e);
+                conn.closed = true;
+                break;
+            }
+        }
+    }
+
+    for line in drain_complete_lines(&mut conn.read_buf) {
+        let response = if let Some(session) = &mut conn.session {
+            if let Some(result) = try_handle_auth_command(&line, auth, tokens, session) {
+                result
+            } else {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                if parts.len() >= 2 && requires_auth(parts[1]) && !session.authenticated {
+                    "ERROR: authentication required for this action".to_string()
+                } else {
+                    process_command(&line, gadgets, conn.conn_id, conn.outbound_tx.clone(), session.format)
+                }
+            }
+        } else {
+            match parse_hello(&line) {
+                Some(session) => {
+                    let reply = hello_reply(&session);
+                    conn.session = Some(session);
+                    reply
+                }
+                None => {
+                    conn.closed = true;
+                    "ERROR: expected HELLO <version> [format=line|json] handshake".to_string()
+                }
+            }
+        };
+        conn.write_buf.extend_from_slice(response.as_bytes());
+        conn.write_buf.push(b'\n');
+    }
+}
+
+fn poll_write(conn: &mut PolledConnection) {
+    while conn.write_pos < conn.write_buf.len() {
+        match conn.stream.write(&conn.write_buf[conn.write_pos..]) {
+            Ok(0) => {
+                conn.closed = true;
+                break;
+            }
+            Ok(n) => conn.write_pos += n,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(e) => {
+                eprintln!("Error writing to polled client: {}", e);
+                conn.closed = true;
+                break;
+            }
+        }
+    }
+    if conn.write_pos == conn.write_buf.len() {
+        conn.write_buf.clear();
+        conn.write_pos = 0;
+    }
+}
+
+/// A connection's single outbound half, shared between the foreground
+/// command loop and the background writer thread that pushes async
+/// `EVENT`s, so the two can never interleave bytes on the same TCP
+/// stream (which has no message boundaries of its own).
+type SharedWriter = Arc<Mutex<TcpStream>>;
+
+fn write_line(writer: &SharedWriter, line: &str) -> std::io::Result<()> {
+    let mut guard = writer.lock().unwrap();
+    writeln!(guard, "{}", line)?;
+    guard.flush()
+}
+
+fn write_bytes(writer: &SharedWriter, bytes: &[u8]) -> std::io::Result<()> {
+    let mut guard = writer.lock().unwrap();
+    guard.write_all(bytes)?;
+    guard.flush()
 }
 
 fn handle_client(
-    mut stream: TcpStream,
+    stream: TcpStream,
     gadgets: Arc<Mutex<HashMap<String, Box<dyn GadgetHandler>>>>,
+    conn_id: ConnectionId,
+    auth: Arc<AuthRegistry>,
+    tokens: Arc<TokenStore>,
 ) {
-    let reader = BufReader::new(stream.try_clone().unwrap());
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let writer: SharedWriter = Arc::new(Mutex::new(stream));
+
+    // The first frame a client sends must be a `HELLO` handshake; a
+    // connection that skips it never reaches the command loop below,
+    // so there's no way to send `receive`/`assert`/etc. without one.
+    let mut hello_line = String::new();
+    let mut session = match reader.read_line(&mut hello_line) {
+        Ok(0) => return, // EOF before handshake
+        Ok(_) => match parse_hello(&hello_line) {
+            Some(session) => {
+                let reply = hello_reply(&session);
+                if write_line(&writer, &reply).is_err() {
+                    return;
+                }
+                println!(
+                    "Connection {} negotiated protocol version {} ({:?} format)",
+                    conn_id, session.version, session.format
+                );
+                session
+            }
+            None => {
+                let _ = write_line(&writer, "ERROR: expected HELLO <version> [format=line|json] handshake");
+                return;
+            }
+        },
+        Err(e) => {
+            eprintln!("Error reading handshake from client: {}", e);
+            return;
+        }
+    };
 
-    for line in reader.lines() {
-        match line {
-            Ok(line) => {
-                let response = process_command(&line, &gadgets);
-                writeln!(stream, "{}", response).unwrap();
-                stream.flush().unwrap();
+    // Events pushed asynchronously (currently dataspace `observe`/tap
+    // `subscribe` notifications) are written by a dedicated thread,
+    // but through the same `writer` mutex the foreground loop uses
+    // below, so a response write and an event write can never
+    // interleave into a single corrupted line for this connection.
+    let (outbound_tx, outbound_rx) = mpsc::channel::<Value>();
+    let event_writer = Arc::clone(&writer);
+    let writer_thread = thread::spawn(move || {
+        for event in outbound_rx {
+            if write_line(&event_writer, &format!("EVENT {}", event)).is_err() {
+                break;
             }
+        }
+    });
+
+    loop {
+        let first_byte = match reader.fill_buf() {
+            Ok([]) => break, // EOF
+            Ok(buf) => buf[0],
             Err(e) => {
                 eprintln!("Error reading from client: {}", e);
                 break;
             }
+        };
+
+        if Value::looks_like_binary_tag(first_byte) {
+            let request = match Value::read_from(&mut reader) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("Error decoding binary frame: {}", e);
+                    break;
+                }
+            };
+            let response = if binary_action_requires_auth(&request) && !session.authenticated {
+                unauthenticated_error()
+            } else {
+                process_binary_command(request, &gadgets, conn_id, outbound_tx.clone())
+            };
+            let mut bytes = Vec::new();
+            response.encode(&mut bytes);
+            if write_bytes(&writer, &bytes).is_err() {
+                break;
+            }
+        } else {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => break, // EOF
+                Ok(_) => {
+                    if let Some(result) = try_handle_auth_command(&line, &auth, &tokens, &mut session) {
+                        if write_line(&writer, &result).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+
+                    let parts: Vec<&str> = line.split_whitespace().collect();
+                    let response = if parts.len() >= 2 && requires_auth(parts[1]) && !session.authenticated {
+                        "ERROR: authentication required for this action".to_string()
+                    } else {
+                        process_command(&line, &gadgets, conn_id, outbound_tx.clone(), session.format)
+                    };
+                    if write_line(&writer, &response).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error reading from client: {}", e);
+                    break;
+                }
+            }
         }
     }
+
+    // Let every gadget clean up anything it was holding on this
+    // connection's behalf (dataspace assertions and observers, tap
+    // subscriptions), which also drops the outbound senders those
+    // held; then drop this connection's own share of the channel so
+    // the writer thread exits.
+    {
+        let mut gadgets_lock = gadgets.lock().unwrap();
+        for gadget in gadgets_lock.values_mut() {
+            gadget.disconnect(conn_id);
+        }
+    }
+    drop(outbound_tx);
+    let _ = writer_thread.join();
+}
+
+/// Handle `AUTH <user> <password>` and `RESUME <token>`, the two
+/// connection-level commands that aren't routed to a gadget. Returns
+/// `Some(reply)` if the line was one of these (whether it succeeded
+/// or not), or `None` if it wasn't and should fall through to the
+/// normal gadget dispatch.
+fn try_handle_auth_command(
+    line: &str,
+    auth: &AuthRegistry,
+    tokens: &TokenStore,
+    session: &mut Session,
+) -> Option<String> {
+    let trimmed = line.trim();
+
+    if let Some(rest) = trimmed.strip_prefix("AUTH ") {
+        let mut parts = rest.splitn(2, ' ');
+        let username = parts.next().unwrap_or("");
+        let password = parts.next().unwrap_or("");
+        return Some(if auth.verify(username, password) {
+            session.authenticated = true;
+            session.username = Some(username.to_string());
+            let token = tokens.issue(username);
+            format!("OK token={}", token)
+        } else {
+            "ERROR: authentication failed".to_string()
+        });
+    }
+
+    if let Some(token) = trimmed.strip_prefix("RESUME ") {
+        return Some(match tokens.resume(token.trim()) {
+            Some(username) => {
+                session.authenticated = true;
+                session.username = Some(username);
+                "OK resumed".to_string()
+            }
+            None => "ERROR: invalid or expired token".to_string(),
+        });
+    }
+
+    None
+}
+
+/// The binary framing doesn't go through `try_handle_auth_command`
+/// (its handshake is text-only), so gating a binary `[gadget action
+/// data?]` request on auth just means peeking the action element.
+fn binary_action_requires_auth(request: &Value) -> bool {
+    match request {
+        Value::Sequence(items) if items.len() >= 2 => match &items[1] {
+            Value::Symbol(s) | Value::String(s) => requires_auth(s),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn unauthenticated_error() -> Value {
+    error_record("unauthenticated", "authentication required for this action")
+}
+
+/// Parsed request shared by both framings: which gadget, which
+/// action, the (already-typed) data payload for that action, and the
+/// connection it came from (needed for `assert`/`retract`/`observe`).
+struct Request<'a> {
+    gadget_name: &'a str,
+    action: &'a str,
+    data: Value,
+    conn: ConnectionId,
+    outbound: OutboundSender,
 }
 
 fn process_command(
     command: &str,
     gadgets: &Arc<Mutex<HashMap<String, Box<dyn GadgetHandler>>>>,
+    conn: ConnectionId,
+    outbound: OutboundSender,
+    format: ResponseFormat,
 ) -> String {
-    let parts: Vec<&str> = command.trim().split_whitespace().collect();
+    let parts: Vec<&str> = command.split_whitespace().collect();
 
     if parts.len() < 2 {
-        return "ERROR: Invalid command format. Use: GADGET_NAME COMMAND [DATA]".to_string();
+        let detail = "Invalid command format. Use: GADGET_NAME COMMAND [DATA]";
+        return match format {
+            ResponseFormat::Line => format!("ERROR: {}", detail),
+            ResponseFormat::Json => json_error_response("invalid_command", detail),
+        };
     }
 
     let gadget_name = parts[0];
     let action = parts[1];
     let data = if parts.len() > 2 {
-        parts[2..].join(" ")
+        Value::String(parts[2..].join(" "))
     } else {
-        String::new()
+        Value::String(String::new())
     };
 
-    let mut gadgets_lock = gadgets.lock().unwrap();
+    let request = Request {
+        gadget_name,
+        action,
+        data,
+        conn,
+        outbound,
+    };
 
-    match action {
-        "receive" => {
-            if let Some(gadget) = gadgets_lock.get_mut(gadget_name) {
-                gadget.receive(&data)
-            } else {
-                format!("ERROR: Gadget '{}' not found", gadget_name)
-            }
+    let result = dispatch(request, gadgets);
+    match format {
+        ResponseFormat::Line => format_line_response(action, result),
+        ResponseFormat::Json => format_json_response(gadget_name, action, result),
+    }
+}
+
+/// Human-readable line responses: `Gadgets: a, b` for `list`, the
+/// result's textual `Value` form otherwise, and `ERROR: ...` for
+/// failures.
+fn format_line_response(action: &str, result: Result<Value, Value>) -> String {
+    match result {
+        Ok(Value::Sequence(items)) if action == "list" => {
+            let names: Vec<String> = items.iter().map(display_string).collect();
+            format!("Gadgets: {}", names.join(", "))
         }
-        "current" => {
-            if let Some(gadget) = gadgets_lock.get(gadget_name) {
-                gadget.current()
-            } else {
-                format!("ERROR: Gadget '{}' not found", gadget_name)
-            }
-        }
-        "create" => {
-            // Create new gadget instances
-            match gadget_name {
-                "counter" => {
-                    gadgets_lock.insert(
-                        data.clone(),
-                        Box::new(CounterHandler {
-                            counter: Counter::new(),
-                        }),
-                    );
-                    format!("Created counter '{}'", data)
-                }
-                "maxcell" => {
-                    let initial = data.parse::<i32>().unwrap_or(0);
-                    gadgets_lock.insert(
-                        format!("maxcell_{}", initial),
-                        Box::new(MaxCellHandler {
-                            maxcell: MaxCell::new(initial),
-                        }),
-                    );
-                    format!("Created maxcell with initial value {}", initial)
-                }
-                _ => format!("ERROR: Unknown gadget type '{}'", gadget_name)
+        Ok(value) => format!("{}", value),
+        Err(value) => format!("ERROR: {}", value),
+    }
+}
+
+/// `{"ok":true,...}` / `{"ok":false,"error":...,"detail":...}` JSON
+/// responses for connections that negotiated `format=json`.
+fn format_json_response(gadget_name: &str, action: &str, result: Result<Value, Value>) -> String {
+    match result {
+        Ok(Value::Sequence(items)) if action == "list" => {
+            let names: Vec<String> = items.iter().map(display_string).collect();
+            let gadgets = names
+                .iter()
+                .map(|n| json_string(n))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!(r#"{{"ok":true,"gadgets":[{}]}}"#, gadgets)
+        }
+        Ok(value) => format!(
+            r#"{{"ok":true,"gadget":{},"value":{}}}"#,
+            json_string(gadget_name),
+            value_to_json(&value)
+        ),
+        Err(value) => {
+            let (code, detail) = error_code_and_detail(&value);
+            json_error_response(&code, &detail)
+        }
+    }
+}
+
+fn json_error_response(code: &str, detail: &str) -> String {
+    format!(
+        r#"{{"ok":false,"error":{},"detail":{}}}"#,
+        json_string(code),
+        json_string(detail)
+    )
+}
+
+/// Pull the machine-readable code and human detail back out of the
+/// `<error code detail>` records `error_record` builds.
+fn error_code_and_detail(value: &Value) -> (String, String) {
+    if let Value::Record { label, fields } = value {
+        if matches!(label.as_ref(), Value::Symbol(s) if s == "error") {
+            if let [Value::Symbol(code), Value::String(detail)] = fields.as_slice() {
+                return (code.clone(), detail.clone());
             }
         }
+    }
+    ("unknown_error".to_string(), format!("{}", value))
+}
+
+/// Render a `Value` as a JSON literal. Symbols have no JSON
+/// equivalent, so they're encoded as strings.
+fn value_to_json(value: &Value) -> String {
+    match value {
+        Value::Boolean(b) => b.to_string(),
+        Value::SignedInteger(n) => n.to_string(),
+        Value::Double(d) => d.to_string(),
+        Value::String(s) => json_string(s),
+        Value::Symbol(s) => json_string(s),
+        Value::ByteString(b) => json_string(&b.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()),
+        Value::Sequence(items) | Value::Set(items) => {
+            format!("[{}]", items.iter().map(value_to_json).collect::<Vec<_>>().join(","))
+        }
+        Value::Record { label, fields } => format!(
+            r#"{{"label":{},"fields":[{}]}}"#,
+            value_to_json(label),
+            fields.iter().map(value_to_json).collect::<Vec<_>>().join(",")
+        ),
+        Value::Dictionary(entries) => format!(
+            "{{{}}}",
+            entries
+                .iter()
+                .map(|(k, v)| format!("{}:{}", json_string(&display_string(k)), value_to_json(v)))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn process_binary_command(
+    request: Value,
+    gadgets: &Arc<Mutex<HashMap<String, Box<dyn GadgetHandler>>>>,
+    conn: ConnectionId,
+    outbound: OutboundSender,
+) -> Value {
+    let (gadget_name, action, data) = match request {
+        Value::Sequence(mut items) if items.len() == 2 || items.len() == 3 => {
+            let data = if items.len() == 3 { items.pop().unwrap() } else { Value::Symbol("".to_string()) };
+            let action = items.pop().unwrap();
+            let gadget_name = items.pop().unwrap();
+            (gadget_name, action, data)
+        }
+        other => return error_record("invalid_request", &format!("expected [gadget action data?], got {}", other)),
+    };
+
+    let gadget_name = match &gadget_name {
+        Value::Symbol(s) | Value::String(s) => s.clone(),
+        other => return error_record("invalid_request", &format!("gadget name must be a symbol, got {}", other)),
+    };
+    let action = match &action {
+        Value::Symbol(s) | Value::String(s) => s.clone(),
+        other => return error_record("invalid_request", &format!("action must be a symbol, got {}", other)),
+    };
+
+    let request = Request {
+        gadget_name: &gadget_name,
+        action: &action,
+        data,
+        conn,
+        outbound,
+    };
+
+    dispatch(request, gadgets).unwrap_or_else(|e| e)
+}
+
+/// Shared dispatch core: looks up the gadget, runs the action, and
+/// returns either its typed result or a typed `error` record -- both
+/// framings format this the same way, they just differ in encoding.
+fn dispatch(
+    request: Request,
+    gadgets: &Arc<Mutex<HashMap<String, Box<dyn GadgetHandler>>>>,
+) -> Result<Value, Value> {
+    let mut gadgets_lock = gadgets.lock().unwrap();
+
+    match request.action {
+        "receive" => match gadgets_lock.get_mut(request.gadget_name) {
+            Some(gadget) => Ok(gadget.receive(request.data)),
+            None => Err(error_record("gadget_not_found", &format!("gadget '{}' not found", request.gadget_name))),
+        },
+        "current" => match gadgets_lock.get(request.gadget_name) {
+            Some(gadget) => Ok(gadget.current()),
+            None => Err(error_record("gadget_not_found", &format!("gadget '{}' not found", request.gadget_name))),
+        },
+        "assert" => match gadgets_lock.get_mut(request.gadget_name) {
+            Some(gadget) => Ok(gadget.assert(request.conn, request.data)),
+            None => Err(error_record("gadget_not_found", &format!("gadget '{}' not found", request.gadget_name))),
+        },
+        "retract" => match gadgets_lock.get_mut(request.gadget_name) {
+            Some(gadget) => Ok(gadget.retract(request.conn, request.data)),
+            None => Err(error_record("gadget_not_found", &format!("gadget '{}' not found", request.gadget_name))),
+        },
+        "observe" => match gadgets_lock.get_mut(request.gadget_name) {
+            Some(gadget) => Ok(gadget.observe(request.conn, request.data, request.outbound)),
+            None => Err(error_record("gadget_not_found", &format!("gadget '{}' not found", request.gadget_name))),
+        },
+        "subscribe" => match gadgets_lock.get_mut(request.gadget_name) {
+            Some(gadget) => Ok(gadget.subscribe(request.conn, request.outbound)),
+            None => Err(error_record("gadget_not_found", &format!("gadget '{}' not found", request.gadget_name))),
+        },
+        "unsubscribe" => match gadgets_lock.get_mut(request.gadget_name) {
+            Some(gadget) => Ok(gadget.unsubscribe(request.conn)),
+            None => Err(error_record("gadget_not_found", &format!("gadget '{}' not found", request.gadget_name))),
+        },
+        "create" => match request.gadget_name {
+            "counter" => {
+                let name = display_string(&request.data);
+                gadgets_lock.insert(
+                    name.clone(),
+                    Box::new(CounterHandler {
+                        counter: Counter::new(),
+                        subs: HashMap::new(),
+                    }),
+                );
+                Ok(Value::String(format!("Created counter '{}'", name)))
+            }
+            "maxcell" => {
+                let initial = match &request.data {
+                    Value::SignedInteger(n) => *n as i32,
+                    Value::String(s) => s.parse::<i32>().unwrap_or(0),
+                    _ => 0,
+                };
+                gadgets_lock.insert(
+                    format!("maxcell_{}", initial),
+                    Box::new(MaxCellHandler {
+                        maxcell: MaxCell::new(initial),
+                        subs: HashMap::new(),
+                    }),
+                );
+                Ok(Value::String(format!("Created maxcell with initial value {}", initial)))
+            }
+            other => Err(error_record("unknown_gadget_type", &format!("unknown gadget type '{}'", other))),
+        },
         "list" => {
-            let keys: Vec<String> = gadgets_lock.keys().cloned().collect();
-            format!("Gadgets: {}", keys.join(", "))
+            let keys: Vec<Value> = gadgets_lock.keys().cloned().map(Value::String).collect();
+            Ok(Value::Sequence(keys))
+        }
+        other => Err(error_record("unknown_action", &format!("unknown action '{}'", other))),
+    }
+}
+
+fn display_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Symbol(s) => s.clone(),
+        other => format!("{}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_auth_covers_mutating_and_observing_actions() {
+        for action in ["receive", "create", "assert", "retract", "observe", "subscribe", "unsubscribe"] {
+            assert!(requires_auth(action), "{action} should require authentication");
+        }
+        for action in ["current", "list"] {
+            assert!(!requires_auth(action), "{action} should stay open to unauthenticated connections");
+        }
+    }
+
+    #[test]
+    fn subscribe_delivers_events_and_unsubscribe_stops_them() {
+        let mut handler = CounterHandler {
+            counter: Counter::new(),
+            subs: HashMap::new(),
+        };
+        let (tx, rx) = mpsc::channel();
+        handler.subscribe(1, tx);
+
+        handler.receive(Value::Symbol("increment".to_string()));
+        assert!(rx.try_recv().is_ok(), "subscribe should have delivered the increment event");
+
+        handler.unsubscribe(1);
+        handler.receive(Value::Symbol("increment".to_string()));
+        assert!(rx.try_recv().is_err(), "no events should arrive after unsubscribe");
+    }
+
+    #[test]
+    fn disconnect_stops_events_like_unsubscribe() {
+        let mut handler = CounterHandler {
+            counter: Counter::new(),
+            subs: HashMap::new(),
+        };
+        let (tx, rx) = mpsc::channel();
+        handler.subscribe(1, tx);
+        handler.disconnect(1);
+
+        handler.receive(Value::Symbol("increment".to_string()));
+        assert!(rx.try_recv().is_err(), "no events should arrive for a connection that disconnected");
+    }
+
+    /// Drives `start_polled` through a real `TcpStream`, since that's
+    /// the only way to exercise its non-blocking accept/read/write
+    /// loop honestly: handshake, authenticate, and run a
+    /// receive/reply round trip through an actual socket.
+    #[test]
+    fn start_polled_serves_a_real_connection() {
+        let addr = "127.0.0.1:19998";
+        let server = Arc::new(GadgetServer::new());
+        let poll_server = Arc::clone(&server);
+        thread::spawn(move || {
+            let _ = poll_server.start_polled(addr);
+        });
+
+        let stream = connect_with_retry(addr);
+        let mut writer = stream.try_clone().expect("clone stream for writing");
+        let mut reader = BufReader::new(stream);
+
+        writer.write_all(b"HELLO 1\n").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("HELLO"), "unexpected handshake reply: {}", line);
+
+        writer.write_all(b"AUTH admin admin\n").unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("OK"), "unexpected auth reply: {}", line);
+
+        writer.write_all(b"counter receive increment\n").unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.contains("changed"), "unexpected receive reply: {}", line);
+    }
+
+    fn connect_with_retry(addr: &str) -> TcpStream {
+        for _ in 0..50 {
+            if let Ok(stream) = TcpStream::connect(addr) {
+                return stream;
+            }
+            thread::sleep(Duration::from_millis(20));
         }
-        _ => format!("ERROR: Unknown action '{}'", action),
+        panic!("polled server never started listening on {}", addr);
     }
-}
\ No newline at end of file
+}
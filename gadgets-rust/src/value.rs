@@ -0,0 +1,403 @@
+// Structured value type modeled on the Preserves data model
+// (https://preserves.dev), as used by Syndicate. Gives gadgets a
+// typed, self-describing alternative to passing raw strings around.
+
+use std::fmt;
+use std::io::{self, Read};
+
+/// A structured, self-describing value.
+///
+/// This is a deliberately small subset of the full Preserves type
+/// system: enough to describe gadget state, incoming data, and
+/// `Effect`s without losing type information along the way.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Boolean(bool),
+    SignedInteger(i64),
+    Double(f64),
+    String(String),
+    ByteString(Vec<u8>),
+    Symbol(String),
+    Sequence(Vec<Value>),
+    Record { label: Box<Value>, fields: Vec<Value> },
+    Dictionary(Vec<(Value, Value)>),
+    Set(Vec<Value>),
+}
+
+/// One-byte tags identifying each `Value` variant in the binary
+/// encoding. Chosen so that none of them collide with the printable
+/// ASCII range the line protocol uses for its first byte, which is
+/// what lets the server tell the two framings apart.
+mod tag {
+    pub const BOOLEAN_FALSE: u8 = 0x80;
+    pub const BOOLEAN_TRUE: u8 = 0x81;
+    pub const SIGNED_INTEGER: u8 = 0x82;
+    pub const DOUBLE: u8 = 0x83;
+    pub const STRING: u8 = 0x84;
+    pub const BYTE_STRING: u8 = 0x85;
+    pub const SYMBOL: u8 = 0x86;
+    pub const SEQUENCE: u8 = 0x87;
+    pub const RECORD: u8 = 0x88;
+    pub const DICTIONARY: u8 = 0x89;
+    pub const SET: u8 = 0x8a;
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    UnexpectedEof,
+    UnknownTag(u8),
+    InvalidUtf8,
+    IntegerTooWide(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of input"),
+            DecodeError::UnknownTag(t) => write!(f, "unknown value tag 0x{:02x}", t),
+            DecodeError::InvalidUtf8 => write!(f, "invalid utf-8 in string"),
+            DecodeError::IntegerTooWide(len) => {
+                write!(f, "signed integer length {} exceeds 8 bytes", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Upper bound on how much capacity a single `Vec::with_capacity`
+/// reserves up front for a decoded element/entry count. The count
+/// comes straight off the wire before any of its claimed elements
+/// have actually been read, so a hostile or corrupt count (e.g.
+/// `0xFFFFFFFF`) must not be able to force a multi-gigabyte
+/// allocation; legitimately large collections still decode correctly,
+/// `push` just grows the `Vec` past this as real elements arrive.
+const MAX_PREALLOCATED_ELEMENTS: usize = 4096;
+
+impl Value {
+    /// True if `byte` could only ever appear as the first byte of a
+    /// binary-encoded `Value`. The line protocol's first byte is
+    /// always printable ASCII (a gadget name), so the server uses
+    /// this to choose which framing to parse a frame with.
+    pub fn looks_like_binary_tag(byte: u8) -> bool {
+        byte >= 0x80
+    }
+
+    /// Encode `self` into the canonical binary form: a one-byte tag
+    /// followed by the payload. Integers are length-prefixed
+    /// big-endian two's-complement; compound values are
+    /// length-prefixed by element count.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Boolean(false) => out.push(tag::BOOLEAN_FALSE),
+            Value::Boolean(true) => out.push(tag::BOOLEAN_TRUE),
+            Value::SignedInteger(n) => {
+                out.push(tag::SIGNED_INTEGER);
+                let bytes = n.to_be_bytes();
+                out.push(bytes.len() as u8);
+                out.extend_from_slice(&bytes);
+            }
+            Value::Double(d) => {
+                out.push(tag::DOUBLE);
+                out.extend_from_slice(&d.to_be_bytes());
+            }
+            Value::String(s) => {
+                out.push(tag::STRING);
+                encode_len_prefixed_bytes(out, s.as_bytes());
+            }
+            Value::ByteString(b) => {
+                out.push(tag::BYTE_STRING);
+                encode_len_prefixed_bytes(out, b);
+            }
+            Value::Symbol(s) => {
+                out.push(tag::SYMBOL);
+                encode_len_prefixed_bytes(out, s.as_bytes());
+            }
+            Value::Sequence(items) => {
+                out.push(tag::SEQUENCE);
+                encode_count(out, items.len());
+                for item in items {
+                    item.encode(out);
+                }
+            }
+            Value::Record { label, fields } => {
+                out.push(tag::RECORD);
+                label.encode(out);
+                encode_count(out, fields.len());
+                for field in fields {
+                    field.encode(out);
+                }
+            }
+            Value::Dictionary(entries) => {
+                out.push(tag::DICTIONARY);
+                encode_count(out, entries.len());
+                for (k, v) in entries {
+                    k.encode(out);
+                    v.encode(out);
+                }
+            }
+            Value::Set(items) => {
+                out.push(tag::SET);
+                encode_count(out, items.len());
+                for item in items {
+                    item.encode(out);
+                }
+            }
+        }
+    }
+
+    /// Read a single binary-encoded `Value` directly off a stream,
+    /// without needing the whole frame buffered up front: each tag
+    /// carries enough of its own length information (fixed-size for
+    /// scalars, length-prefixed for strings and collections) that
+    /// the reader always knows exactly how many more bytes to pull.
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Value> {
+        let mut tag_byte = [0u8; 1];
+        r.read_exact(&mut tag_byte)?;
+        match tag_byte[0] {
+            tag::BOOLEAN_FALSE => Ok(Value::Boolean(false)),
+            tag::BOOLEAN_TRUE => Ok(Value::Boolean(true)),
+            tag::SIGNED_INTEGER => {
+                let mut len_byte = [0u8; 1];
+                r.read_exact(&mut len_byte)?;
+                let len = len_byte[0] as usize;
+                if len > 8 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, DecodeError::IntegerTooWide(len)));
+                }
+                let mut bytes = vec![0u8; len];
+                r.read_exact(&mut bytes)?;
+                let mut buf = [0u8; 8];
+                buf[8 - len..].copy_from_slice(&bytes);
+                Ok(Value::SignedInteger(i64::from_be_bytes(buf)))
+            }
+            tag::DOUBLE => {
+                let mut bytes = [0u8; 8];
+                r.read_exact(&mut bytes)?;
+                Ok(Value::Double(f64::from_be_bytes(bytes)))
+            }
+            tag::STRING => {
+                let bytes = read_len_prefixed_from(r)?;
+                String::from_utf8(bytes)
+                    .map(Value::String)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, DecodeError::InvalidUtf8))
+            }
+            tag::BYTE_STRING => Ok(Value::ByteString(read_len_prefixed_from(r)?)),
+            tag::SYMBOL => {
+                let bytes = read_len_prefixed_from(r)?;
+                String::from_utf8(bytes)
+                    .map(Value::Symbol)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, DecodeError::InvalidUtf8))
+            }
+            tag::SEQUENCE => {
+                let count = read_count_from(r)?;
+                let mut items = Vec::with_capacity(count.min(MAX_PREALLOCATED_ELEMENTS));
+                for _ in 0..count {
+                    items.push(Value::read_from(r)?);
+                }
+                Ok(Value::Sequence(items))
+            }
+            tag::RECORD => {
+                let label = Box::new(Value::read_from(r)?);
+                let count = read_count_from(r)?;
+                let mut fields = Vec::with_capacity(count.min(MAX_PREALLOCATED_ELEMENTS));
+                for _ in 0..count {
+                    fields.push(Value::read_from(r)?);
+                }
+                Ok(Value::Record { label, fields })
+            }
+            tag::DICTIONARY => {
+                let count = read_count_from(r)?;
+                let mut entries = Vec::with_capacity(count.min(MAX_PREALLOCATED_ELEMENTS));
+                for _ in 0..count {
+                    let k = Value::read_from(r)?;
+                    let v = Value::read_from(r)?;
+                    entries.push((k, v));
+                }
+                Ok(Value::Dictionary(entries))
+            }
+            tag::SET => {
+                let count = read_count_from(r)?;
+                let mut items = Vec::with_capacity(count.min(MAX_PREALLOCATED_ELEMENTS));
+                for _ in 0..count {
+                    items.push(Value::read_from(r)?);
+                }
+                Ok(Value::Set(items))
+            }
+            other => Err(io::Error::new(io::ErrorKind::InvalidData, DecodeError::UnknownTag(other))),
+        }
+    }
+
+    /// Build the `Record` that a gadget `Effect` round-trips as:
+    /// `<changed 5>`, `<custom "k" "v">`, `<noop>`. A `Changed` payload
+    /// that parses as an integer becomes a `SignedInteger` field;
+    /// anything else stays a `String`.
+    pub fn from_effect(effect: &crate::lib::Effect) -> Value {
+        use crate::lib::Effect;
+        match effect {
+            Effect::Changed(s) => Value::Record {
+                label: Box::new(Value::Symbol("changed".to_string())),
+                fields: vec![s
+                    .parse::<i64>()
+                    .map(Value::SignedInteger)
+                    .unwrap_or_else(|_| Value::String(s.clone()))],
+            },
+            Effect::Noop => Value::Record {
+                label: Box::new(Value::Symbol("noop".to_string())),
+                fields: vec![],
+            },
+            Effect::Custom(k, v) => Value::Record {
+                label: Box::new(Value::Symbol("custom".to_string())),
+                fields: vec![Value::String(k.clone()), Value::String(v.clone())],
+            },
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Human-readable textual form, loosely following Preserves
+    /// textual syntax: `#t`/`#f`, bare symbols, quoted strings,
+    /// `[a b c]` sequences, `<label a b>` records.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Boolean(true) => write!(f, "#t"),
+            Value::Boolean(false) => write!(f, "#f"),
+            Value::SignedInteger(n) => write!(f, "{}", n),
+            Value::Double(d) => write!(f, "{}", d),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::ByteString(b) => {
+                write!(f, "#[")?;
+                for byte in b {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, "]")
+            }
+            Value::Symbol(s) => write!(f, "{}", s),
+            Value::Sequence(items) => write_joined(f, "[", items, "]"),
+            Value::Record { label, fields } => {
+                write!(f, "<{}", label)?;
+                for field in fields {
+                    write!(f, " {}", field)?;
+                }
+                write!(f, ">")
+            }
+            Value::Dictionary(entries) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}: {}", k, v)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Set(items) => write_joined(f, "#{", items, "}"),
+        }
+    }
+}
+
+fn write_joined(f: &mut fmt::Formatter<'_>, open: &str, items: &[Value], close: &str) -> fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    write!(f, "{}", close)
+}
+
+fn encode_count(out: &mut Vec<u8>, count: usize) {
+    out.extend_from_slice(&(count as u32).to_be_bytes());
+}
+
+fn encode_len_prefixed_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn read_count_from<R: Read>(r: &mut R) -> io::Result<usize> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes) as usize)
+}
+
+fn read_len_prefixed_from<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_count_from(r)?;
+    // Same reasoning as `MAX_PREALLOCATED_ELEMENTS`: `len` is
+    // attacker-controlled and unread, so grow into it incrementally
+    // via `take`/`read_to_end` rather than zeroing a `len`-byte buffer
+    // up front.
+    let mut bytes = Vec::with_capacity(len.min(MAX_PREALLOCATED_ELEMENTS));
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, DecodeError::UnexpectedEof));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: &Value) -> Value {
+        let mut bytes = Vec::new();
+        value.encode(&mut bytes);
+        Value::read_from(&mut &bytes[..]).expect("decode what we just encoded")
+    }
+
+    #[test]
+    fn round_trips_scalars() {
+        for value in [
+            Value::Boolean(true),
+            Value::Boolean(false),
+            Value::SignedInteger(-12345),
+            Value::Double(3.5),
+            Value::String("hello".to_string()),
+            Value::ByteString(vec![1, 2, 3]),
+            Value::Symbol("ok".to_string()),
+        ] {
+            assert_eq!(round_trip(&value), value);
+        }
+    }
+
+    #[test]
+    fn round_trips_nested_collections() {
+        let value = Value::Record {
+            label: Box::new(Value::Symbol("changed".to_string())),
+            fields: vec![
+                Value::Sequence(vec![Value::SignedInteger(1), Value::SignedInteger(2)]),
+                Value::Dictionary(vec![(Value::Symbol("k".to_string()), Value::String("v".to_string()))]),
+                Value::Set(vec![Value::Boolean(true)]),
+            ],
+        };
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn read_from_rejects_unknown_tag() {
+        let err = Value::read_from(&mut &[0x00u8][..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// Regression test for the allocation-DoS fix above: a claimed
+    /// element count of `u32::MAX` with no backing bytes must fail
+    /// fast with an `UnexpectedEof`, not attempt a multi-gigabyte
+    /// `Vec::with_capacity`.
+    #[test]
+    fn read_from_rejects_oversized_claimed_count_without_allocating() {
+        let mut bytes = vec![tag::SEQUENCE];
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        let err = Value::read_from(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    /// Regression test for a wire-supplied `SIGNED_INTEGER` length
+    /// greater than 8: must be rejected, not panic on `buf[8 -
+    /// len..]`.
+    #[test]
+    fn read_from_rejects_oversized_integer_length() {
+        let bytes = [tag::SIGNED_INTEGER, 200];
+        let err = Value::read_from(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}